@@ -2,6 +2,7 @@ use alloc::{
     boxed::Box,
     collections::BTreeMap,
     string::{String, ToString},
+    vec::Vec,
 };
 use core::{fmt, num::FpCategory, result};
 use serde::ser::{self, Impossible, Serialize};
@@ -39,97 +40,213 @@ impl ser::Error for Error {
     }
 }
 
-pub struct Serializer {
-    buf: String,
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::Message(
+            "sink returned an error while writing; if writing through \
+             IoWrite, see IoWrite::io_error for the underlying error"
+                .into(),
+        )
+    }
+}
+
+/// Adapts an [`std::io::Write`] sink so it can be used anywhere a
+/// [`core::fmt::Write`] sink is expected, e.g. with [`to_writer`].
+///
+/// `fmt::Write`'s error type carries no detail, so a failed write (broken
+/// pipe, disk full, ...) would otherwise surface as an opaque `Error`. To
+/// keep the original [`std::io::Error`] around, pass `to_writer` a `&mut`
+/// reference to a named `IoWrite` rather than moving it in, then call
+/// [`IoWrite::io_error`] once `to_writer` returns `Err`.
+#[cfg(feature = "std")]
+pub struct IoWrite<W> {
+    inner: W,
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W> IoWrite<W> {
+    pub fn new(inner: W) -> Self {
+        IoWrite {
+            inner,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Takes the [`std::io::Error`] from the most recent failed write, if
+    /// any.
+    pub fn io_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> fmt::Write for IoWrite<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Which flavour of Rison the outermost value is written as.
+///
+/// O-Rison and A-Rison are the reduced grammars Rison defines for embedding
+/// in URLs: an object without its surrounding `(` … `)`, or an array without
+/// its surrounding `!(` … `)`. The mode only ever affects the outermost
+/// container; it is consumed as soon as the first container is entered, so
+/// nested objects/arrays always keep their full delimiters.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Standard,
+    ORison,
+    ARison,
+}
+
+/// How object keys are ordered in the output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KeyOrder {
+    /// Lexicographic order, regardless of serialization order (the default).
+    Sorted,
+    /// The order keys were serialized in.
+    Insertion,
+}
+
+pub struct Serializer<W> {
+    writer: W,
+    mode: Mode,
+    key_order: KeyOrder,
+}
+
+impl<W> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Serializer {
+            writer,
+            mode: Mode::Standard,
+            key_order: KeyOrder::Sorted,
+        }
+    }
+
+    /// Serialize the outermost value in O-Rison form: an object without its
+    /// surrounding `(` … `)`. Serializing anything other than a top-level map
+    /// or struct is an error.
+    pub fn orison(mut self) -> Self {
+        self.mode = Mode::ORison;
+        self
+    }
+
+    /// Serialize the outermost value in A-Rison form: an array without its
+    /// surrounding `!(` … `)`. Serializing anything other than a top-level
+    /// sequence or tuple is an error.
+    pub fn arison(mut self) -> Self {
+        self.mode = Mode::ARison;
+        self
+    }
+
+    /// Emit object keys in the order they were serialized instead of sorting
+    /// them lexicographically. Applies to every object at every nesting
+    /// level, not just the outermost one.
+    pub fn preserve_order(mut self) -> Self {
+        self.key_order = KeyOrder::Insertion;
+        self
+    }
+
+    fn check_standard(&self) -> Result<()> {
+        if self.mode == Mode::Standard {
+            Ok(())
+        } else {
+            Err(Error::Message(
+                "O-Rison/A-Rison mode requires a top-level container of the matching kind".into(),
+            ))
+        }
+    }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a, W: fmt::Write> ser::Serializer for &'a mut Serializer<W> {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = SeqSerializer<'a>;
-    type SerializeTuple = SeqSerializer<'a>;
-    type SerializeTupleStruct = SeqSerializer<'a>;
-    type SerializeTupleVariant = SeqSerializer<'a>;
-    type SerializeMap = MapSerializer<'a>;
-    type SerializeStruct = MapSerializer<'a>;
-    type SerializeStructVariant = MapSerializer<'a>;
+    type SerializeSeq = SeqSerializer<'a, W>;
+    type SerializeTuple = SeqSerializer<'a, W>;
+    type SerializeTupleStruct = SeqSerializer<'a, W>;
+    type SerializeTupleVariant = SeqSerializer<'a, W>;
+    type SerializeMap = MapSerializer<'a, W>;
+    type SerializeStruct = MapSerializer<'a, W>;
+    type SerializeStructVariant = MapSerializer<'a, W>;
 
     fn serialize_bool(self, v: bool) -> Result<()> {
-        if v {
-            self.buf.push_str("!t");
-        } else {
-            self.buf.push_str("!f");
-        }
+        self.check_standard()?;
+        self.writer.write_str(if v { "!t" } else { "!f" })?;
         Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_i128(self, v: i128) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_u128(self, v: u128) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        int_to_string(&mut self.writer, v)
     }
 
     fn serialize_f32(self, v: f32) -> Result<()> {
+        self.check_standard()?;
         match v.classify() {
             FpCategory::Nan | FpCategory::Infinite => self.serialize_unit(),
-            _ => {
-                float_to_string(&mut self.buf, v);
-                Ok(())
-            }
+            _ => float_to_string(&mut self.writer, v),
         }
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
+        self.check_standard()?;
         match v.classify() {
             FpCategory::Nan | FpCategory::Infinite => self.serialize_unit(),
-            _ => {
-                float_to_string(&mut self.buf, v);
-                Ok(())
-            }
+            _ => float_to_string(&mut self.writer, v),
         }
     }
 
@@ -139,15 +256,15 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        escaped_str(&mut self.buf, v);
-        Ok(())
+        self.check_standard()?;
+        escaped_str(&mut self.writer, v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
         use serde::ser::SerializeSeq;
-        let mut seq = self.serialize_seq(Some(v.len())).unwrap();
+        let mut seq = self.serialize_seq(Some(v.len()))?;
         for b in v {
-            seq.serialize_element(b).unwrap();
+            seq.serialize_element(b)?;
         }
         seq.end()
     }
@@ -164,7 +281,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_unit(self) -> Result<()> {
-        self.buf.push_str("!n");
+        self.check_standard()?;
+        self.writer.write_str("!n")?;
         Ok(())
     }
 
@@ -198,17 +316,30 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize,
     {
-        self.buf.push('(');
-        self.serialize_str(variant).unwrap();
-        self.buf.push(':');
+        self.check_standard()?;
+        self.writer.write_char('(')?;
+        self.serialize_str(variant)?;
+        self.writer.write_char(':')?;
         value.serialize(&mut *self)?;
-        self.buf.push(')');
+        self.writer.write_char(')')?;
         Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.buf.push_str("!(");
-        Ok(SeqSerializer::new(self))
+        let suppress_delims = match self.mode {
+            Mode::ORison => {
+                return Err(Error::Message(
+                    "O-Rison mode requires a top-level object".into(),
+                ))
+            }
+            Mode::ARison => true,
+            Mode::Standard => false,
+        };
+        self.mode = Mode::Standard;
+        if !suppress_delims {
+            self.writer.write_str("!(")?;
+        }
+        Ok(SeqSerializer::new(self, suppress_delims))
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
@@ -230,14 +361,25 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.buf.push('(');
-        self.serialize_str(variant).unwrap();
-        self.buf.push(':');
+        self.check_standard()?;
+        self.writer.write_char('(')?;
+        self.serialize_str(variant)?;
+        self.writer.write_char(':')?;
         self.serialize_seq(Some(len))
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Ok(MapSerializer::new(self))
+        let suppress_delims = match self.mode {
+            Mode::ARison => {
+                return Err(Error::Message(
+                    "A-Rison mode requires a top-level array".into(),
+                ))
+            }
+            Mode::ORison => true,
+            Mode::Standard => false,
+        };
+        self.mode = Mode::Standard;
+        Ok(MapSerializer::new(self, suppress_delims))
     }
 
     fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
@@ -251,14 +393,15 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.buf.push('(');
-        self.serialize_str(variant).unwrap();
-        self.buf.push(':');
+        self.check_standard()?;
+        self.writer.write_char('(')?;
+        self.serialize_str(variant)?;
+        self.writer.write_char(':')?;
         self.serialize_map(Some(len))
     }
 }
 
-impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeSeq for SeqSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -266,17 +409,19 @@ impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
     where
         T: Serialize,
     {
-        self.write_sep();
+        self.write_sep()?;
         value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.ser.buf.push(')');
+        if !self.suppress_delims {
+            self.ser.writer.write_char(')')?;
+        }
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeTuple for SeqSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -284,17 +429,19 @@ impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
     where
         T: Serialize,
     {
-        self.write_sep();
+        self.write_sep()?;
         value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.ser.buf.push(')');
+        if !self.suppress_delims {
+            self.ser.writer.write_char(')')?;
+        }
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -302,17 +449,19 @@ impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
     where
         T: Serialize,
     {
-        self.write_sep();
+        self.write_sep()?;
         value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.ser.buf.push(')');
+        if !self.suppress_delims {
+            self.ser.writer.write_char(')')?;
+        }
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -320,67 +469,151 @@ impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
     where
         T: Serialize,
     {
-        self.write_sep();
+        self.write_sep()?;
         value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.ser.buf.push_str("))");
+        self.ser.writer.write_str("))")?;
         Ok(())
     }
 }
 
 #[doc(hidden)]
-pub struct SeqSerializer<'a> {
-    ser: &'a mut Serializer,
+pub struct SeqSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
     first: bool,
+    suppress_delims: bool,
 }
 
-impl<'a> SeqSerializer<'a> {
-    fn new(ser: &'a mut Serializer) -> SeqSerializer<'a> {
-        SeqSerializer { ser, first: true }
+impl<'a, W: fmt::Write> SeqSerializer<'a, W> {
+    fn new(ser: &'a mut Serializer<W>, suppress_delims: bool) -> SeqSerializer<'a, W> {
+        SeqSerializer {
+            ser,
+            first: true,
+            suppress_delims,
+        }
     }
 
-    fn write_sep(&mut self) {
+    fn write_sep(&mut self) -> Result<()> {
         if self.first {
             self.first = false;
         } else {
-            self.ser.buf.push(',');
+            self.ser.writer.write_char(',')?;
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+/// The value half of a map entry: a byte range into [`MapSerializer::value_buf`]
+/// rather than an owned `String`, so serializing a value never allocates.
+type ValueSpan = (usize, usize);
+
+/// Entries accumulated by a [`MapSerializer`], backed by whichever structure
+/// matches the configured [`KeyOrder`].
+enum Entries {
+    Sorted(BTreeMap<String, ValueSpan>),
+    Insertion(Vec<(String, ValueSpan)>),
+}
+
+impl Entries {
+    fn new(key_order: KeyOrder) -> Entries {
+        match key_order {
+            KeyOrder::Sorted => Entries::Sorted(BTreeMap::new()),
+            KeyOrder::Insertion => Entries::Insertion(Vec::new()),
+        }
+    }
+
+    /// Inserts `key`/`span`, matching `BTreeMap::insert`'s semantics even in
+    /// insertion-order mode: a repeated key overwrites the existing value in
+    /// place rather than appearing twice in the output.
+    fn insert(&mut self, key: String, span: ValueSpan) {
+        match self {
+            Entries::Sorted(map) => {
+                map.insert(key, span);
+            }
+            Entries::Insertion(vec) => match vec.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => entry.1 = span,
+                None => vec.push((key, span)),
+            },
         }
     }
 }
 
 #[doc(hidden)]
-pub struct MapSerializer<'a> {
-    ser: &'a mut Serializer,
-    map: BTreeMap<String, String>,
+pub struct MapSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+    entries: Entries,
+    value_buf: String,
     key: Option<String>,
+    suppress_delims: bool,
 }
 
-impl<'a> MapSerializer<'a> {
-    fn new(ser: &'a mut Serializer) -> MapSerializer<'a> {
+impl<'a, W: fmt::Write> MapSerializer<'a, W> {
+    fn new(ser: &'a mut Serializer<W>, suppress_delims: bool) -> MapSerializer<'a, W> {
+        let entries = Entries::new(ser.key_order);
         MapSerializer {
             ser,
-            map: BTreeMap::new(),
+            entries,
+            value_buf: String::new(),
             key: None,
+            suppress_delims,
         }
     }
 
-    fn write_object(self, end: &str) {
-        self.ser.buf.push('(');
-        for (i, (key, value)) in self.map.iter().enumerate() {
-            if i != 0 {
-                self.ser.buf.push(',');
+    /// Serializes `value` into `self.value_buf` and records the byte range it
+    /// occupies there, rather than allocating a fresh `String` per entry.
+    fn record_value<T: ?Sized>(&mut self, key: String, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let start = self.value_buf.len();
+        let mut value_ser = Serializer {
+            writer: &mut self.value_buf,
+            mode: Mode::Standard,
+            key_order: self.ser.key_order,
+        };
+        value.serialize(&mut value_ser)?;
+        let end = self.value_buf.len();
+        self.entries.insert(key, (start, end));
+        Ok(())
+    }
+
+    fn write_object(self, end: &str) -> Result<()> {
+        if !self.suppress_delims {
+            self.ser.writer.write_char('(')?;
+        }
+        match &self.entries {
+            Entries::Sorted(map) => {
+                for (i, (key, &(start, stop))) in map.iter().enumerate() {
+                    if i != 0 {
+                        self.ser.writer.write_char(',')?;
+                    }
+                    self.ser.writer.write_str(key.as_str())?;
+                    self.ser.writer.write_char(':')?;
+                    self.ser.writer.write_str(&self.value_buf[start..stop])?;
+                }
             }
-            self.ser.buf.push_str(key.as_str());
-            self.ser.buf.push(':');
-            self.ser.buf.push_str(value.as_str());
+            Entries::Insertion(vec) => {
+                for (i, (key, (start, stop))) in vec.iter().enumerate() {
+                    if i != 0 {
+                        self.ser.writer.write_char(',')?;
+                    }
+                    self.ser.writer.write_str(key.as_str())?;
+                    self.ser.writer.write_char(':')?;
+                    self.ser.writer.write_str(&self.value_buf[*start..*stop])?;
+                }
+            }
+        }
+        if !self.suppress_delims {
+            self.ser.writer.write_str(end)?;
         }
-        self.ser.buf.push_str(end);
+        Ok(())
     }
 }
 
-impl<'a> ser::SerializeMap for MapSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeMap for MapSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -400,17 +633,16 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
     where
         T: Serialize,
     {
-        self.map.insert(self.key.take().unwrap(), to_string(value)?);
-        Ok(())
+        let key = self.key.take().unwrap();
+        self.record_value(key, value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.write_object(")");
-        Ok(())
+        self.write_object(")")
     }
 }
 
-impl<'a> ser::SerializeStruct for MapSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeStruct for MapSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -419,18 +651,16 @@ impl<'a> ser::SerializeStruct for MapSerializer<'a> {
         T: Serialize,
     {
         let mut buf = String::with_capacity(key.len());
-        escaped_str(&mut buf, key);
-        self.map.insert(buf, to_string(value)?);
-        Ok(())
+        escaped_str(&mut buf, key)?;
+        self.record_value(buf, value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.write_object(")");
-        Ok(())
+        self.write_object(")")
     }
 }
 
-impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
+impl<'a, W: fmt::Write> ser::SerializeStructVariant for MapSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -439,14 +669,12 @@ impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
         T: Serialize,
     {
         let mut buf = String::with_capacity(key.len());
-        escaped_str(&mut buf, key);
-        self.map.insert(buf, to_string(value)?);
-        Ok(())
+        escaped_str(&mut buf, key)?;
+        self.record_value(buf, value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.write_object("))");
-        Ok(())
+        self.write_object("))")
     }
 }
 
@@ -470,53 +698,43 @@ impl<'a> ser::Serializer for &'a mut MapKeySerializer {
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_i128(self, v: i128) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_u128(self, v: u128) -> Result<()> {
-        int_to_string(&mut self.buf, v);
-        Ok(())
+        int_to_string(&mut self.buf, v)
     }
 
     fn serialize_f32(self, _v: f32) -> Result<()> {
@@ -533,8 +751,7 @@ impl<'a> ser::Serializer for &'a mut MapKeySerializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        escaped_str(&mut self.buf, v);
-        Ok(())
+        escaped_str(&mut self.buf, v)
     }
 
     fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
@@ -566,8 +783,7 @@ impl<'a> ser::Serializer for &'a mut MapKeySerializer {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<()> {
-        escaped_str(&mut self.buf, variant);
-        Ok(())
+        escaped_str(&mut self.buf, variant)
     }
 
     fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<()>
@@ -636,33 +852,35 @@ impl<'a> ser::Serializer for &'a mut MapKeySerializer {
 }
 
 #[inline]
-fn int_to_string<I: itoa::Integer>(s: &mut String, i: I) {
+fn int_to_string<W: fmt::Write, I: itoa::Integer>(w: &mut W, i: I) -> Result<()> {
     use itoa::Buffer;
     let mut buf = Buffer::new();
-    s.push_str(buf.format(i));
+    w.write_str(buf.format(i))?;
+    Ok(())
 }
 
 #[inline]
-fn float_to_string<F: ryu::Float>(s: &mut String, f: F) {
+fn float_to_string<W: fmt::Write, F: ryu::Float>(w: &mut W, f: F) -> Result<()> {
     use ryu::Buffer;
     let mut buf = Buffer::new();
-    s.push_str(buf.format(f))
+    w.write_str(buf.format(f))?;
+    Ok(())
 }
 
-fn escaped_str(s: &mut String, value: &str) {
+fn escaped_str<W: fmt::Write>(w: &mut W, value: &str) -> Result<()> {
     let bytes = value.as_bytes();
 
     if bytes.is_empty() {
-        s.push_str("''");
-        return;
+        w.write_str("''")?;
+        return Ok(());
     }
 
     if !NOT_ID_START[bytes[0] as usize] && !bytes[1..].iter().any(|b| NOT_ID[*b as usize]) {
-        s.push_str(value);
-        return;
+        w.write_str(value)?;
+        return Ok(());
     }
 
-    s.push('\'');
+    w.write_char('\'')?;
     let mut start = 0;
     for (i, &b) in bytes.iter().enumerate() {
         if b != b'!' && b != b'\'' {
@@ -670,17 +888,18 @@ fn escaped_str(s: &mut String, value: &str) {
         }
 
         if start < i {
-            s.push_str(&value[start..i]);
+            w.write_str(&value[start..i])?;
         }
-        s.push('!');
-        s.push(b.into());
+        w.write_char('!')?;
+        w.write_char(b.into())?;
 
         start = i + 1;
     }
     if start < bytes.len() {
-        s.push_str(&value[start..]);
+        w.write_str(&value[start..])?;
     }
-    s.push('\'');
+    w.write_char('\'')?;
+    Ok(())
 }
 
 const T: bool = true;
@@ -726,13 +945,834 @@ static NOT_ID_START: [bool; 256] = [
     F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // f
 ];
 
+/// Serializes `value` as Rison text into `writer`.
+///
+/// This streams directly into any [`core::fmt::Write`] sink, so it avoids the
+/// intermediate `String` allocation that [`to_string`] needs. To target an
+/// [`std::io::Write`] sink instead, wrap it in [`IoWrite`].
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: fmt::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::new(writer);
+    value.serialize(&mut ser)
+}
+
 pub fn to_string<T>(value: &T) -> Result<String>
 where
     T: ?Sized + Serialize,
 {
-    let mut ser = Serializer {
-        buf: String::with_capacity(16),
-    };
-    value.serialize(&mut ser)?;
-    Ok(ser.buf)
+    let mut s = String::with_capacity(16);
+    to_writer(&mut s, value)?;
+    Ok(s)
+}
+
+/// Serializes `value` into `writer` as O-Rison: an object without its
+/// surrounding `(` … `)`. Returns an error if `value` does not serialize as a
+/// top-level map or struct.
+pub fn to_writer_orison<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: fmt::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::new(writer).orison();
+    value.serialize(&mut ser)
+}
+
+/// Serializes `value` as O-Rison text: an object without its surrounding
+/// `(` … `)`. Returns an error if `value` does not serialize as a top-level
+/// map or struct.
+pub fn to_string_orison<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut s = String::with_capacity(16);
+    to_writer_orison(&mut s, value)?;
+    Ok(s)
+}
+
+/// Serializes `value` into `writer` as A-Rison: an array without its
+/// surrounding `!(` … `)`. Returns an error if `value` does not serialize as
+/// a top-level sequence or tuple.
+pub fn to_writer_arison<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: fmt::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::new(writer).arison();
+    value.serialize(&mut ser)
+}
+
+/// Serializes `value` as A-Rison text: an array without its surrounding
+/// `!(` … `)`. Returns an error if `value` does not serialize as a top-level
+/// sequence or tuple.
+pub fn to_string_arison<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut s = String::with_capacity(16);
+    to_writer_arison(&mut s, value)?;
+    Ok(s)
+}
+
+/// Like [`to_writer`], but objects keep the key order they were serialized in
+/// instead of being sorted lexicographically.
+pub fn to_writer_preserve_order<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: fmt::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::new(writer).preserve_order();
+    value.serialize(&mut ser)
+}
+
+/// Like [`to_string`], but objects keep the key order they were serialized in
+/// instead of being sorted lexicographically.
+pub fn to_string_preserve_order<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut s = String::with_capacity(16);
+    to_writer_preserve_order(&mut s, value)?;
+    Ok(s)
+}
+
+/// An in-memory Rison document.
+///
+/// Building this tree instead of text lets callers inspect, transform, or
+/// re-key a value before handing it to [`to_string`] (or [`to_writer`]) for
+/// final serialization.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+/// Drives `value`'s `Serialize` impl into a [`Value`] tree instead of text.
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ValueSerializer)
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(vec) => vec.serialize(serializer),
+            Value::Object(map) => map.serialize(serializer),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeValueMap;
+    type SerializeStruct = SerializeValueMap;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value> {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| Error::Message("i128 value does not fit in Value::Int".into()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| Error::Message("u64 value does not fit in Value::Int".into()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value> {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| Error::Message("u128 value does not fit in Value::Int".into()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Array(v.iter().map(|&b| Value::Int(b.into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Value>
+    where
+        T: Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Value>
+    where
+        T: Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: Serialize,
+    {
+        let mut map = BTreeMap::new();
+        map.insert(String::from(variant), to_value(value)?);
+        Ok(Value::Object(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeVec {
+            vec: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            variant,
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(SerializeValueMap {
+            map: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            variant,
+            map: BTreeMap::new(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeVec {
+    vec: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.vec))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.vec))
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.vec))
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    vec: Vec<Value>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        let mut map = BTreeMap::new();
+        map.insert(String::from(self.variant), Value::Array(self.vec));
+        Ok(Value::Object(map))
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeValueMap {
+    map: BTreeMap<String, Value>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeValueMap {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let mut ser = ValueMapKeySerializer {
+            buf: String::with_capacity(4),
+        };
+        key.serialize(&mut ser)?;
+        self.key = Some(ser.buf);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.map.insert(self.key.take().unwrap(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Object(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeValueMap {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.map.insert(String::from(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Object(self.map))
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    map: BTreeMap<String, Value>,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.map.insert(String::from(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        let mut outer = BTreeMap::new();
+        outer.insert(String::from(self.variant), Value::Object(self.map));
+        Ok(Value::Object(outer))
+    }
+}
+
+/// Like [`MapKeySerializer`] but for building [`Value`] keys: produces a
+/// plain (unescaped) `String` rather than Rison-escaped text.
+struct ValueMapKeySerializer {
+    buf: String,
+}
+
+impl<'a> ser::Serializer for &'a mut ValueMapKeySerializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        int_to_string(&mut self.buf, v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.buf.push_str(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.buf.push_str(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: Serialize,
+    {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::KeyMustBeAString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use serde::ser::{SerializeMap as _, SerializeStruct as _};
+
+    /// Serializes the key `"k"` twice, to exercise `Entries::insert`'s
+    /// overwrite behaviour independent of `key_order`.
+    struct DupMap;
+
+    impl Serialize for DupMap {
+        fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+        {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("k", &1)?;
+            map.serialize_entry("k", &2)?;
+            map.end()
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_overwrite_in_sorted_mode() {
+        assert_eq!(to_string(&DupMap).unwrap(), "(k:2)");
+    }
+
+    #[test]
+    fn duplicate_keys_overwrite_in_insertion_mode() {
+        assert_eq!(to_string_preserve_order(&DupMap).unwrap(), "(k:2)");
+    }
+
+    /// Serializes struct fields `b, a, c`, in that (deliberately
+    /// out-of-lexical) order, to distinguish `preserve_order` from a
+    /// backing store that happens to sort anyway.
+    struct OutOfOrderStruct;
+
+    impl Serialize for OutOfOrderStruct {
+        fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+        {
+            let mut s = serializer.serialize_struct("OutOfOrderStruct", 3)?;
+            s.serialize_field("b", &1)?;
+            s.serialize_field("a", &2)?;
+            s.serialize_field("c", &3)?;
+            s.end()
+        }
+    }
+
+    #[test]
+    fn preserve_order_keeps_serialization_order() {
+        assert_eq!(
+            to_string_preserve_order(&OutOfOrderStruct).unwrap(),
+            "(b:1,a:2,c:3)"
+        );
+    }
+
+    #[test]
+    fn default_mode_sorts_keys_lexicographically() {
+        assert_eq!(to_string(&OutOfOrderStruct).unwrap(), "(a:2,b:1,c:3)");
+    }
+
+    #[test]
+    fn to_value_builds_an_object_tree() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), 1i32);
+        map.insert("b".to_string(), 2i32);
+        let mut expected = BTreeMap::new();
+        expected.insert("a".to_string(), Value::Int(1));
+        expected.insert("b".to_string(), Value::Int(2));
+        assert_eq!(to_value(&map).unwrap(), Value::Object(expected));
+    }
+
+    #[test]
+    fn to_value_builds_an_array() {
+        assert_eq!(
+            to_value(&vec![1, 2, 3]).unwrap(),
+            Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)])
+        );
+    }
+
+    #[test]
+    fn to_value_rejects_u64_that_does_not_fit_in_i64() {
+        assert!(to_value(&u64::MAX).is_err());
+    }
+
+    #[test]
+    fn to_value_rejects_i128_that_does_not_fit_in_i64() {
+        let overflowing = i128::from(i64::MIN) - 1;
+        assert!(to_value(&overflowing).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    struct AlwaysErrWriter;
+
+    #[cfg(feature = "std")]
+    impl fmt::Write for AlwaysErrWriter {
+        fn write_str(&mut self, _s: &str) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    struct RawBytes<'a>(&'a [u8]);
+
+    #[cfg(feature = "std")]
+    impl<'a> Serialize for RawBytes<'a> {
+        fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where
+            S: ser::Serializer,
+        {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn to_writer_propagates_errors_instead_of_panicking() {
+        assert!(to_writer(&mut AlwaysErrWriter, &RawBytes(b"hello")).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    struct FailingIo;
+
+    #[cfg(feature = "std")]
+    impl std::io::Write for FailingIo {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "pipe closed",
+            ))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_write_recovers_the_underlying_io_error() {
+        let mut writer = IoWrite::new(FailingIo);
+        assert!(to_writer(&mut writer, &1u32).is_err());
+        let io_err = writer.io_error().expect("io_error should be set");
+        assert_eq!(io_err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn orison_rejects_non_map_top_level() {
+        assert!(to_string_orison(&5i32).is_err());
+    }
+
+    #[test]
+    fn orison_accepts_map_top_level() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), 1);
+        map.insert("b".to_string(), 2);
+        assert_eq!(to_string_orison(&map).unwrap(), "a:1,b:2");
+    }
+
+    #[test]
+    fn arison_rejects_non_seq_top_level() {
+        assert!(to_string_arison(&5i32).is_err());
+    }
+
+    #[test]
+    fn arison_accepts_seq_top_level() {
+        assert_eq!(to_string_arison(&vec![1, 2, 3]).unwrap(), "1,2,3");
+    }
 }